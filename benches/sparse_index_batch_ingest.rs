@@ -0,0 +1,60 @@
+use std::hint::black_box;
+
+use cosdata::models::types::SparseVector;
+use cosdata::storage::inverted_index_sparse_ann_basic::InvertedIndexSparseAnnBasicTSHashmap;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const QUANTIZATION: u8 = 64;
+const DIMENSIONS: u32 = 2048;
+const ENTRIES_PER_VECTOR: usize = 64;
+
+fn make_vectors(count: u32) -> Vec<SparseVector> {
+    (0..count)
+        .map(|vector_id| {
+            let entries = (0..ENTRIES_PER_VECTOR)
+                .map(|i| {
+                    let dim_index = (vector_id.wrapping_mul(31).wrapping_add(i as u32)) % DIMENSIONS;
+                    let value = ((i + 1) as f32) / (ENTRIES_PER_VECTOR as f32 + 1.0);
+                    (dim_index, value)
+                })
+                .collect();
+            SparseVector {
+                vector_id,
+                entries,
+            }
+        })
+        .collect()
+}
+
+fn bench_ingest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sparse_index_ingest");
+
+    for &count in &[100u32, 1_000, 10_000] {
+        group.bench_with_input(BenchmarkId::new("per_vector", count), &count, |b, &count| {
+            b.iter_batched(
+                || (InvertedIndexSparseAnnBasicTSHashmap::new(QUANTIZATION), make_vectors(count)),
+                |(index, vectors)| {
+                    for vector in vectors {
+                        index.add_sparse_vector(black_box(vector)).unwrap();
+                    }
+                },
+                criterion::BatchSize::LargeInput,
+            )
+        });
+
+        group.bench_with_input(BenchmarkId::new("batch", count), &count, |b, &count| {
+            b.iter_batched(
+                || (InvertedIndexSparseAnnBasicTSHashmap::new(QUANTIZATION), make_vectors(count)),
+                |(index, vectors)| {
+                    index.add_sparse_vectors_batch(black_box(vectors)).unwrap();
+                },
+                criterion::BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_ingest);
+criterion_main!(benches);
@@ -1,8 +1,11 @@
 use arcshift::ArcShift;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use core::array::from_fn;
-use dashmap::DashMap;
 use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
 use std::fs::OpenOptions;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::{path::Path, sync::RwLock};
 
 use std::sync::Arc;
@@ -228,6 +231,36 @@ impl InvertedIndexSparseAnnBasic {
     }
 }
 
+/// Writes `value` as a LEB128 varint: 7 bits of payload per byte, high bit set on every
+/// byte but the last. Small deltas between sorted, ascending `vector_id`s cost one byte.
+fn write_varint<W: Write>(writer: &mut W, mut value: u32) -> std::io::Result<()> {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        writer.write_u8(byte)?;
+        if value == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a LEB128 varint written by [`write_varint`].
+fn read_varint<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut result = 0u32;
+    let mut shift = 0u32;
+    loop {
+        let byte = reader.read_u8()?;
+        result |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
+}
+
 fn get_permutations(num: u8) -> Vec<u8> {
     let mut result = vec![num];
     let mut one_positions = Vec::new();
@@ -261,6 +294,85 @@ fn get_permutations(num: u8) -> Vec<u8> {
     result
 }
 
+/// A compact, exact membership set over `vector_id`s, one bit per id.
+///
+/// Unlike [`PerformantFixedSet`], which trades exactness for a fixed bit budget and answers
+/// "might be a member", a `Bitset` grows to fit every inserted id exactly, so independently
+/// built bitsets can be unioned or intersected without the false positives a probabilistic
+/// set would introduce.
+#[derive(Debug, Default, Clone)]
+pub struct Bitset {
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    pub fn new() -> Self {
+        Self { words: Vec::new() }
+    }
+
+    fn word_index(vector_id: u32) -> usize {
+        vector_id as usize / 64
+    }
+
+    /// Sets the bit for `vector_id`, growing the backing storage if needed.
+    pub fn insert(&mut self, vector_id: u32) {
+        let word_index = Self::word_index(vector_id);
+        if word_index >= self.words.len() {
+            self.words.resize(word_index + 1, 0);
+        }
+        self.words[word_index] |= 1u64 << (vector_id % 64);
+    }
+
+    pub fn contains(&self, vector_id: u32) -> bool {
+        self.words
+            .get(Self::word_index(vector_id))
+            .is_some_and(|word| word & (1u64 << (vector_id % 64)) != 0)
+    }
+
+    /// Unions `other` into `self` in place, returning whether any bit changed.
+    pub fn union(&mut self, other: &Bitset) -> bool {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        let mut changed = false;
+        for (word, &other_word) in self.words.iter_mut().zip(&other.words) {
+            let merged = *word | other_word;
+            if merged != *word {
+                changed = true;
+                *word = merged;
+            }
+        }
+        changed
+    }
+
+    /// Returns the bitwise intersection of `self` and `other` as a new `Bitset`.
+    pub fn intersect(&self, other: &Bitset) -> Bitset {
+        let words = self
+            .words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| a & b)
+            .collect();
+        Bitset { words }
+    }
+
+    /// Iterates the set `vector_id`s in ascending order, scanning words and peeling off the
+    /// lowest set bit with `trailing_zeros` each step.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_index, &word)| {
+            let mut remaining = word;
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+                let bit = remaining.trailing_zeros();
+                remaining &= remaining - 1;
+                Some(word_index as u32 * 64 + bit)
+            })
+        })
+    }
+}
+
 pub struct InvertedIndexSparseAnnNodeBasicTSHashmap {
     pub dim_index: u32,
     pub implicit: bool,
@@ -271,6 +383,8 @@ pub struct InvertedIndexSparseAnnNodeBasicTSHashmap {
     // len = number of bits used to store quantized value (4, 5, 6)
     pub bit_fixed_sets: Vec<RwLock<PerformantFixedSet>>,
     pub quantization: u8,
+    // len = quantization; exact membership per bucket, unioned/intersected for boolean queries
+    pub bucket_bitsets: Vec<RwLock<Bitset>>,
 }
 
 #[derive(Clone)]
@@ -308,6 +422,11 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
             )));
         }
 
+        let mut bucket_bitsets = Vec::with_capacity(quantization as usize);
+        for _ in 0..quantization {
+            bucket_bitsets.push(RwLock::new(Bitset::new()));
+        }
+
         Self {
             dim_index,
             implicit,
@@ -316,6 +435,7 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
             lazy_children: ProbLazyItemArray::new(),
             bit_fixed_sets,
             quantization,
+            bucket_bitsets,
         }
     }
 
@@ -366,6 +486,10 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
             .write()
             .unwrap()
             .insert(vector_id);
+        self.bucket_bitsets[quantized_value as usize]
+            .write()
+            .unwrap()
+            .insert(vector_id);
         // println!("vector_id -> {vector_id}");
         for i in 0..4 {
             if (quantized_value & (1u8 << i)) != 0 {
@@ -375,6 +499,58 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
         // println!("vector_id_2 -> {vector_id}");
     }
 
+    /// Inserts many `(value, vector_id)` pairs destined for this node in one pass.
+    ///
+    /// Groups the batch by quantized value first, then takes each bucket's `data`/fixed-set/
+    /// bitset locks exactly once for the whole group instead of once per `vector_id`, which is
+    /// what [`insert`](Self::insert) does when called in a loop.
+    pub fn insert_batch(&self, entries: &[(f32, u32)]) {
+        let mut by_quantized: HashMap<u8, Vec<u32>> = HashMap::new();
+        for &(value, vector_id) in entries {
+            by_quantized
+                .entry(self.quantize(value))
+                .or_default()
+                .push(vector_id);
+        }
+
+        for (quantized_value, vector_ids) in by_quantized {
+            self.data
+                .get_or_create(quantized_value, || Pagepool::default());
+            self.data.mutate(quantized_value, |x| {
+                let mut vecof_vec_id = x.unwrap();
+                for &vector_id in &vector_ids {
+                    vecof_vec_id.push(vector_id);
+                }
+                Some(vecof_vec_id)
+            });
+
+            {
+                let mut exclusive = self.exclusive_key_fixed_sets[quantized_value as usize]
+                    .write()
+                    .unwrap();
+                for &vector_id in &vector_ids {
+                    exclusive.insert(vector_id);
+                }
+            }
+            {
+                let mut bucket_bitset = self.bucket_bitsets[quantized_value as usize]
+                    .write()
+                    .unwrap();
+                for &vector_id in &vector_ids {
+                    bucket_bitset.insert(vector_id);
+                }
+            }
+            for i in 0..4 {
+                if (quantized_value & (1u8 << i)) != 0 {
+                    let mut bit_set = self.bit_fixed_sets[i].write().unwrap();
+                    for &vector_id in &vector_ids {
+                        bit_set.insert(vector_id);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn search_fixed_sets(&self, vector_id: u32) -> Option<u8> {
         let mut index = 0u8;
         for i in 0..4 {
@@ -390,6 +566,19 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
         }
     }
 
+    /// Exactly resolves which quantized bucket `vector_id` belongs to at this node, by
+    /// scanning the exact `bucket_bitsets` rather than reconstructing the key from the
+    /// probabilistic `bit_fixed_sets`/`exclusive_key_fixed_sets` planes (see
+    /// [`find_key_of_id`](Self::find_key_of_id)), which only answer "might be a member" and
+    /// can combine into a wrong key for a candidate -- unacceptable for `search`, which is
+    /// supposed to compute an exact dot product.
+    pub fn find_bucket_of_id(&self, vector_id: u32) -> Option<u8> {
+        self.bucket_bitsets
+            .iter()
+            .position(|bucket| bucket.read().unwrap().contains(vector_id))
+            .map(|index| index as u8)
+    }
+
     pub fn find_key_of_id(&self, vector_id: u32) -> Option<u8> {
         let index = self.search_fixed_sets(vector_id)?;
         let found = self.exclusive_key_fixed_sets[index as usize]
@@ -412,6 +601,165 @@ impl InvertedIndexSparseAnnNodeBasicTSHashmap {
         None
     }
 
+    /// Writes this node to `writer` as a fixed header followed by its posting data, then
+    /// recursively writes every child depth-first, back-patching the header's child offset
+    /// table once each child's offset is known. Returns the offset this node was written at.
+    ///
+    /// On-disk layout:
+    /// ```text
+    /// dim_index:     u32        (4 bytes)
+    /// implicit:      u8         (1 byte)
+    /// quantization:  u8         (1 byte)
+    /// child_offsets: [u32; 16]  (64 bytes, 0 => no child in that slot)
+    /// bucket_count:  u16
+    /// buckets:       bucket_count * (key: u8, count: u32, deltas: varint * count)
+    /// ```
+    /// Posting lists are stored sorted and delta-encoded as LEB128 varints, so dense runs of
+    /// `vector_id`s cost close to one byte each instead of four.
+    pub fn serialize<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        cache: &ProbCache,
+    ) -> std::io::Result<u32> {
+        let header_offset = writer.stream_position()? as u32;
+
+        writer.write_u32::<LittleEndian>(self.dim_index)?;
+        writer.write_u8(self.implicit as u8)?;
+        writer.write_u8(self.quantization)?;
+
+        // Reserved now, back-patched below once every child has been written and its
+        // offset is known.
+        let child_table_offset = writer.stream_position()?;
+        for _ in 0..16 {
+            writer.write_u32::<LittleEndian>(0)?;
+        }
+
+        let mut buckets: Vec<(u8, Vec<u32>)> = self
+            .data
+            .to_list()
+            .into_iter()
+            .map(|(key, pool)| {
+                let mut ids: Vec<u32> = pool.iter().collect();
+                ids.sort_unstable();
+                (key, ids)
+            })
+            .collect();
+        buckets.sort_by_key(|(key, _)| *key);
+
+        writer.write_u16::<LittleEndian>(buckets.len() as u16)?;
+        for (key, ids) in &buckets {
+            writer.write_u8(*key)?;
+            writer.write_u32::<LittleEndian>(ids.len() as u32)?;
+            let mut previous = 0u32;
+            for &id in ids {
+                write_varint(writer, id - previous)?;
+                previous = id;
+            }
+        }
+
+        let mut child_offsets = [0u32; 16];
+        for child_index in 0..16 {
+            if let Some(child) = self.lazy_children.get(child_index) {
+                let child_node = unsafe { &*child }.try_get_data(cache).unwrap();
+                child_offsets[child_index] = child_node.serialize(writer, cache)?;
+            }
+        }
+
+        let end_offset = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(child_table_offset))?;
+        for offset in &child_offsets {
+            writer.write_u32::<LittleEndian>(*offset)?;
+        }
+        writer.seek(SeekFrom::Start(end_offset))?;
+
+        Ok(header_offset)
+    }
+
+    /// Reads a node's header and posting data back from `offset`. Children are *not*
+    /// followed here — their offsets are returned alongside the node so that callers (in
+    /// particular [`hydrate`](Self::hydrate)) can decide how to read them back in.
+    pub fn deserialize<R: Read + Seek>(
+        reader: &mut R,
+        offset: FileOffset,
+    ) -> std::io::Result<(Self, [u32; 16])> {
+        reader.seek(SeekFrom::Start(offset.0 as u64))?;
+
+        let dim_index = reader.read_u32::<LittleEndian>()?;
+        let implicit = reader.read_u8()? != 0;
+        let quantization = reader.read_u8()?;
+
+        let mut child_offsets = [0u32; 16];
+        for slot in &mut child_offsets {
+            *slot = reader.read_u32::<LittleEndian>()?;
+        }
+
+        let node = Self::new(dim_index, implicit, quantization);
+
+        let bucket_count = reader.read_u16::<LittleEndian>()?;
+        for _ in 0..bucket_count {
+            let key = reader.read_u8()?;
+            let count = reader.read_u32::<LittleEndian>()?;
+            node.data.get_or_create(key, || Pagepool::default());
+
+            let mut previous = 0u32;
+            for _ in 0..count {
+                let vector_id = previous + read_varint(reader)?;
+                previous = vector_id;
+
+                node.data.mutate(key, |pool| {
+                    let mut pool = pool.unwrap();
+                    pool.push(vector_id);
+                    Some(pool)
+                });
+                node.exclusive_key_fixed_sets[key as usize]
+                    .write()
+                    .unwrap()
+                    .insert(vector_id);
+                node.bucket_bitsets[key as usize]
+                    .write()
+                    .unwrap()
+                    .insert(vector_id);
+                for i in 0..4 {
+                    if (key & (1u8 << i)) != 0 {
+                        node.bit_fixed_sets[i].write().unwrap().insert(vector_id);
+                    }
+                }
+            }
+        }
+
+        Ok((node, child_offsets))
+    }
+
+    /// Deserializes the node at `offset`, then recursively deserializes every child it records
+    /// and attaches each one with its real on-disk `FileOffset`, using the same
+    /// `ProbLazyItem::new(data, version, version_id, is_latest, offset)` constructor
+    /// `find_or_create_node` uses for freshly-created nodes.
+    ///
+    /// True on-demand paging (resolving a child from its `FileOffset` the first time
+    /// `try_get_data` is called on it, without reading it here) would need a `ProbLazyItem`
+    /// constructor that can attach an offset without already having deserialized data to hand
+    /// it; no such constructor exists on this type, so `load` reads the whole tree up front
+    /// rather than claim laziness it can't deliver.
+    pub fn hydrate<R: Read + Seek>(
+        reader: &mut R,
+        offset: FileOffset,
+        cache: &ProbCache,
+    ) -> std::io::Result<Self> {
+        let (node, child_offsets) = Self::deserialize(reader, offset)?;
+
+        for (child_index, &child_offset) in child_offsets.iter().enumerate() {
+            if child_offset == 0 {
+                continue;
+            }
+            let child_node = Self::hydrate(reader, FileOffset(child_offset as usize), cache)?;
+            node.lazy_children.get_or_insert(child_index, || {
+                ProbLazyItem::new(child_node, 0.into(), 0, false, FileOffset(child_offset as usize))
+            });
+        }
+
+        Ok(node)
+    }
+
     // /// Retrieves a value from the index at the specified dimension index.
     // /// Calculates the path and delegates to `get_value`.
     // pub fn get(&self, dim_index: u32, vector_id: u32, cache: Arc<NodeRegistry>) -> Option<u8> {
@@ -470,6 +818,50 @@ impl InvertedIndexSparseAnnBasicTSHashmap {
         }
     }
 
+    /// Writes the whole tree to `{ver}.index` via [`serialize`](InvertedIndexSparseAnnNodeBasicTSHashmap::serialize),
+    /// so it can later be restored with [`load`](Self::load) instead of rebuilt from scratch.
+    pub fn flush(&self, ver: Hash) -> std::io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(format!("{}.index", *ver))?;
+        let mut writer = BufWriter::new(file);
+        self.root.serialize(&mut writer, &self.cache)?;
+        writer.flush()
+    }
+
+    /// Restores a tree previously written by [`flush`](Self::flush) from `{ver}.index`, via
+    /// [`InvertedIndexSparseAnnNodeBasicTSHashmap::hydrate`], which reads the root and then
+    /// recursively reads every child it records.
+    pub fn load(ver: Hash) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(format!("{}.index", *ver))?;
+        let mut reader = BufReader::new(file);
+
+        let bufmans = Arc::new(BufferManagerFactory::new(
+            Path::new(".").into(),
+            |root, ver: &Hash| root.join(format!("{}.index", **ver)),
+            8192,
+        ));
+        let prop_file = Arc::new(RwLock::new(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open("prop.data")
+                .unwrap(),
+        ));
+        let cache = Arc::new(ProbCache::new(bufmans.clone(), bufmans, prop_file));
+
+        let root = InvertedIndexSparseAnnNodeBasicTSHashmap::hydrate(&mut reader, FileOffset(0), &cache)?;
+
+        Ok(InvertedIndexSparseAnnBasicTSHashmap {
+            root: Arc::new(root),
+            cache,
+        })
+    }
+
     /// Finds the node at a given dimension
     /// Traverses the tree iteratively and returns a reference to the node.
     pub fn find_node(&self, dim_index: u32) -> Option<&InvertedIndexSparseAnnNodeBasicTSHashmap> {
@@ -507,13 +899,538 @@ impl InvertedIndexSparseAnnBasicTSHashmap {
         });
         Ok(())
     }
+
+    /// Bulk-ingests `vectors`, building the tree structure once for the whole batch instead
+    /// of re-walking from the root per entry.
+    ///
+    /// `add_sparse_vector` re-runs `calculate_path`/`find_or_create_node` from the root for
+    /// every nonzero entry, so ingesting N vectors of D dims each does up to N*D root-to-leaf
+    /// walks with repeated `ProbLazyItem` allocations and lock contention along shared path
+    /// prefixes. Here we first collect the union of distinct `dim_index`es touched by the
+    /// batch and walk from the root once per distinct dimension (not once per entry), so
+    /// every needed node, and every node on a shared path prefix, is created once; the walks
+    /// themselves still repeat shared prefixes. Then we fan the postings out to their
+    /// target nodes in parallel with `rayon` so each node's `data`/fixed-set/bitset locks are
+    /// taken once for its whole share of the batch via [`InvertedIndexSparseAnnNodeBasicTSHashmap::insert_batch`].
+    pub fn add_sparse_vectors_batch(&self, vectors: Vec<SparseVector>) -> Result<(), String> {
+        let mut dims = BTreeSet::new();
+        for vector in &vectors {
+            for &(dim_index, value) in &vector.entries {
+                if value != 0.0 {
+                    dims.insert(dim_index);
+                }
+            }
+        }
+
+        let nodes_by_dim: HashMap<u32, &InvertedIndexSparseAnnNodeBasicTSHashmap> = dims
+            .into_iter()
+            .map(|dim_index| {
+                let path = calculate_path(dim_index, self.root.dim_index);
+                let node = self.root.find_or_create_node(&path, &self.cache);
+                (dim_index, node)
+            })
+            .collect();
+
+        let mut postings_by_dim: HashMap<u32, Vec<(f32, u32)>> = HashMap::new();
+        for vector in &vectors {
+            for &(dim_index, value) in &vector.entries {
+                if value != 0.0 {
+                    postings_by_dim
+                        .entry(dim_index)
+                        .or_default()
+                        .push((value, vector.vector_id));
+                }
+            }
+        }
+
+        postings_by_dim
+            .into_par_iter()
+            .for_each(|(dim_index, entries)| nodes_by_dim[&dim_index].insert_batch(&entries));
+
+        Ok(())
+    }
+
+    /// Finds the `k` stored vectors with the highest dot-product score against `query`.
+    ///
+    /// Resolves each query dimension to its node and accumulates `weight * dequantized`
+    /// contributions per `vector_id`, then keeps only the best `k` in a bounded min-heap.
+    /// Uses MaxScore pruning to avoid fully scanning every query term's postings: terms are
+    /// sorted by their upper bound (`weight.max(0.0)`, since the maximum dequantized value is
+    /// `1.0` and a negative weight's own max contribution is `0.0`) and split into a
+    /// "non-essential" prefix whose cumulative upper bound can't beat the current top-k
+    /// threshold `theta`, and an "essential" suffix that is. Only essential terms' postings
+    /// are fully scanned to discover candidates; non-essential terms are applied to
+    /// already-discovered candidates via a `find_bucket_of_id` lookup, and are skipped
+    /// entirely once a candidate's remaining non-essential upper bound can no longer push it
+    /// past `theta`. The split is recomputed every time `theta` rises.
+    pub fn search(&self, query: &SparseVector, k: usize) -> Vec<(u32, f32)> {
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let mut terms: Vec<QueryTerm> = query
+            .entries
+            .iter()
+            .filter(|(_, weight)| *weight != 0.0)
+            .filter_map(|(dim_index, weight)| {
+                let node = self.find_node(*dim_index)?;
+                Some(QueryTerm {
+                    node,
+                    weight: *weight,
+                    // Dequantized values are clamped into [0.0, 1.0], so the true max
+                    // contribution of a negative weight is weight * 0.0 == 0.0, not weight.
+                    upper_bound: weight.max(0.0),
+                })
+            })
+            .collect();
+
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        // Ascending by upper bound: the cumulative sum over a prefix is the most those
+        // (low-impact) terms could possibly contribute to any single candidate.
+        terms.sort_by(|a, b| a.upper_bound.partial_cmp(&b.upper_bound).unwrap());
+        let prefix_sums: Vec<f32> = terms
+            .iter()
+            .scan(0.0f32, |acc, term| {
+                *acc += term.upper_bound;
+                Some(*acc)
+            })
+            .collect();
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        let mut heap: BinaryHeap<ScoredCandidate> = BinaryHeap::with_capacity(k + 1);
+        let mut theta = 0.0f32;
+
+        // terms[essential_start..] are "essential" (fully scanned); terms[..essential_start]
+        // are "non-essential". Starts at 0 since theta starts at 0, and only grows as theta
+        // rises, so a term scanned as essential is never rescanned.
+        let mut essential_start = 0usize;
+        let mut next_unscanned = terms.len();
+
+        // Scan essential terms highest-upper-bound first, so theta rises as fast as
+        // possible and later (lower-bound) terms are more likely to already be skippable.
+        while next_unscanned > essential_start {
+            next_unscanned -= 1;
+            let term = &terms[next_unscanned];
+            let quantization = term.node.quantization as f32;
+
+            for (key, bucket) in term.node.data.to_list() {
+                let dequantized = key as f32 / (quantization - 1.0);
+                let contribution = term.weight * dequantized;
+                for vector_id in bucket.iter() {
+                    *scores.entry(vector_id).or_insert(0.0) += contribution;
+                }
+            }
+
+            refresh_heap(&scores, &mut heap, k);
+            if heap.len() == k {
+                theta = heap.peek().unwrap().score;
+            }
+
+            essential_start = prefix_sums
+                .iter()
+                .position(|&cumulative| cumulative >= theta)
+                .unwrap_or(terms.len())
+                .min(next_unscanned);
+        }
+
+        // Apply non-essential terms to the surviving candidates only, via direct lookup,
+        // bailing out early once the remaining non-essential upper bound can't help anymore.
+        let non_essential = &terms[..essential_start];
+        let mut remaining_ub = vec![0.0f32; non_essential.len()];
+        let mut running = 0.0f32;
+        for (i, term) in non_essential.iter().enumerate().rev() {
+            running += term.upper_bound;
+            remaining_ub[i] = running;
+        }
+
+        let candidates: Vec<u32> = scores.keys().copied().collect();
+        for vector_id in candidates {
+            for (i, term) in non_essential.iter().enumerate() {
+                if scores[&vector_id] + remaining_ub[i] <= theta {
+                    break;
+                }
+                if let Some(key) = term.node.find_bucket_of_id(vector_id) {
+                    let dequantized = key as f32 / (term.node.quantization - 1) as f32;
+                    *scores.get_mut(&vector_id).unwrap() += term.weight * dequantized;
+                }
+            }
+        }
+
+        refresh_heap(&scores, &mut heap, k);
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|candidate| (candidate.vector_id, candidate.score))
+            .collect()
+    }
+
+    /// Returns every `vector_id` that has a nonzero entry in *all* of `dims_required`.
+    ///
+    /// For each dimension, the node's per-bucket bitsets are unioned into a single "touches
+    /// this dimension" bitset; those per-dimension bitsets are then intersected across every
+    /// requested dimension, giving exact conjunctive filtering that the probabilistic
+    /// `exclusive_key_fixed_sets`/`bit_fixed_sets` can't, since those can't be combined across
+    /// dimensions without compounding false positives.
+    pub fn boolean_search(&self, dims_required: &[u32]) -> impl Iterator<Item = u32> {
+        let mut matched: Option<Bitset> = None;
+
+        for &dim_index in dims_required {
+            let Some(node) = self.find_node(dim_index) else {
+                matched = Some(Bitset::new());
+                break;
+            };
+
+            let mut touches_dim = Bitset::new();
+            for bucket in &node.bucket_bitsets {
+                touches_dim.union(&bucket.read().unwrap());
+            }
+
+            matched = Some(match matched {
+                Some(acc) => acc.intersect(&touches_dim),
+                None => touches_dim,
+            });
+        }
+
+        matched
+            .map(|bitset| bitset.iter().collect::<Vec<u32>>())
+            .unwrap_or_default()
+            .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `search`'s non-essential term pass used to resolve a candidate's bucket via
+    /// `find_key_of_id`, which reconstructs the key from probabilistic bit planes and can
+    /// misattribute a candidate to the wrong bucket -- silently corrupting the exact dot
+    /// product `search` is supposed to compute. With enough distinctly-keyed vectors sharing a
+    /// node, the fixed version (`find_bucket_of_id`, an exact `bucket_bitsets` scan) must score
+    /// every candidate the same as a brute-force dot product would.
+    #[test]
+    fn search_scores_match_brute_force_dot_product() {
+        let index = InvertedIndexSparseAnnBasicTSHashmap::new(16);
+
+        let vectors: Vec<SparseVector> = (0..40u32)
+            .map(|vector_id| SparseVector {
+                vector_id,
+                entries: (0..4u32)
+                    .map(|dim_index| {
+                        let value = ((vector_id * 7 + dim_index * 13) % 16) as f32 / 15.0;
+                        (dim_index, value)
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        for vector in &vectors {
+            index.add_sparse_vector(vector.clone()).unwrap();
+        }
+
+        let query = SparseVector {
+            vector_id: 1000,
+            entries: vec![(0, 1.0), (1, 0.5), (2, 0.25), (3, 0.75)],
+        };
+
+        let got = index.search(&query, 5);
+
+        let quantization = 16.0;
+        let mut brute_force: Vec<(u32, f32)> = vectors
+            .iter()
+            .map(|vector| {
+                let score: f32 = vector
+                    .entries
+                    .iter()
+                    .zip(&query.entries)
+                    .map(|(&(_, stored), &(_, weight))| {
+                        let quantized =
+                            (stored * (quantization - 1.0)).clamp(0.0, quantization - 1.0) as u8;
+                        weight * (quantized as f32 / (quantization - 1.0))
+                    })
+                    .sum();
+                (vector.vector_id, score)
+            })
+            .collect();
+        brute_force.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        brute_force.truncate(5);
+
+        assert_eq!(got.len(), brute_force.len());
+        for ((got_id, got_score), (expected_id, expected_score)) in got.iter().zip(&brute_force) {
+            assert_eq!(got_id, expected_id);
+            assert!((got_score - expected_score).abs() < 1e-4);
+        }
+    }
+
+    /// Builds a tree, flushes it to disk, drops the in-memory tree entirely, and re-reads
+    /// postings from a freshly loaded one by `find_node` -- the exact round trip `serialize`/
+    /// `deserialize` exist for.
+    #[test]
+    fn flush_and_load_round_trips_postings() {
+        let index = InvertedIndexSparseAnnBasicTSHashmap::new(16);
+        index.insert(0, 0.2, 1);
+        index.insert(0, 0.8, 2);
+        index.insert(5, 0.5, 3);
+
+        let ver: Hash = 4242u64.into();
+        index.flush(ver).unwrap();
+        drop(index);
+
+        let restored = InvertedIndexSparseAnnBasicTSHashmap::load(ver).unwrap();
+
+        let dim0 = restored.find_node(0).unwrap();
+        let mut dim0_buckets = dim0.data.to_list();
+        dim0_buckets.sort_by_key(|(key, _)| *key);
+        let dim0_ids: Vec<u32> = dim0_buckets
+            .iter()
+            .flat_map(|(_, pool)| pool.iter())
+            .collect();
+        assert_eq!(dim0_ids, vec![1, 2]);
+
+        let dim5 = restored.find_node(5).unwrap();
+        let dim5_ids: Vec<u32> = dim5
+            .data
+            .to_list()
+            .into_iter()
+            .flat_map(|(_, pool)| pool.iter())
+            .collect();
+        assert_eq!(dim5_ids, vec![3]);
+    }
+
+    #[test]
+    fn bitset_union_and_intersect_match_set_semantics() {
+        let mut a = Bitset::new();
+        for id in [1, 5, 130] {
+            a.insert(id);
+        }
+        let mut b = Bitset::new();
+        for id in [5, 64, 130] {
+            b.insert(id);
+        }
+
+        let mut union = a.clone();
+        union.union(&b);
+        assert_eq!(union.iter().collect::<Vec<u32>>(), vec![1, 5, 64, 130]);
+
+        let intersection = a.intersect(&b);
+        assert_eq!(intersection.iter().collect::<Vec<u32>>(), vec![5, 130]);
+    }
+
+    #[test]
+    fn boolean_search_returns_vectors_present_in_every_required_dimension() {
+        let index = InvertedIndexSparseAnnBasicTSHashmap::new(16);
+        index.insert(0, 0.5, 1);
+        index.insert(1, 0.5, 1);
+        index.insert(0, 0.25, 2);
+        index.insert(1, 0.75, 3);
+
+        let mut both = index.boolean_search(&[0, 1]).collect::<Vec<u32>>();
+        both.sort_unstable();
+        assert_eq!(both, vec![1]);
+
+        let mut either = index.boolean_search(&[0]).collect::<Vec<u32>>();
+        either.sort_unstable();
+        assert_eq!(either, vec![1, 2]);
+    }
+
+    #[test]
+    fn int_map_round_trips_sparse_ids_in_ascending_order() {
+        let mut map: IntMap<u8> = IntMap::new();
+        map.insert(5, 9);
+        map.insert(130, 3);
+        map.insert(0, 1);
+
+        assert_eq!(map.get(5), Some(9));
+        assert_eq!(map.get(1), None);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(0, 1), (5, 9), (130, 3)]);
+    }
+
+    #[test]
+    fn dash_map_index_insert_and_get_round_trip_through_int_map() {
+        let index = InvertedIndexSparseAnnBasicDashMap::new();
+        index.insert(3, 0.5, 42);
+
+        assert_eq!(
+            index.get(3, 42),
+            Some(InvertedIndexSparseAnnNodeBasicDashMap::quantize(0.5))
+        );
+        assert_eq!(index.get(3, 99), None);
+    }
+
+    /// `add_sparse_vectors_batch` resolves every touched dimension's node once up front and
+    /// then fans postings out to those nodes in parallel, instead of walking the tree once per
+    /// entry like `add_sparse_vector` -- two dimensions resolving to the same node under that
+    /// parallel fan-out is exactly the kind of slip that would leave this test failing.
+    #[test]
+    fn batch_ingest_matches_per_vector_ingest() {
+        let vectors: Vec<SparseVector> = (0..20u32)
+            .map(|vector_id| SparseVector {
+                vector_id,
+                entries: (0..3u32)
+                    .map(|dim_index| {
+                        let value = ((vector_id * 5 + dim_index * 11) % 16) as f32 / 15.0;
+                        (dim_index, value)
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let sequential = InvertedIndexSparseAnnBasicTSHashmap::new(16);
+        for vector in &vectors {
+            sequential.add_sparse_vector(vector.clone()).unwrap();
+        }
+
+        let batched = InvertedIndexSparseAnnBasicTSHashmap::new(16);
+        batched.add_sparse_vectors_batch(vectors).unwrap();
+
+        let query = SparseVector {
+            vector_id: 1000,
+            entries: vec![(0, 1.0), (1, 0.5), (2, 0.25)],
+        };
+
+        assert_eq!(sequential.search(&query, 5), batched.search(&query, 5));
+    }
+}
+
+/// A single query dimension resolved against the index: the node holding its postings,
+/// the query weight, and the MaxScore upper bound on its contribution to any candidate.
+struct QueryTerm<'a> {
+    node: &'a InvertedIndexSparseAnnNodeBasicTSHashmap,
+    weight: f32,
+    upper_bound: f32,
+}
+
+/// A candidate scored during [`InvertedIndexSparseAnnBasicTSHashmap::search`].
+///
+/// Ordered in reverse of its score so a `BinaryHeap<ScoredCandidate>` behaves as a
+/// bounded min-heap: the lowest-scoring candidate sits at the top and is the first
+/// evicted once the heap grows past `k`, leaving the k-th best score as `theta`.
+/// Equal scores are ranked by ascending `vector_id`, so results are deterministic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScoredCandidate {
+    vector_id: u32,
+    score: f32,
+}
+
+impl Eq for ScoredCandidate {}
+
+impl Ord for ScoredCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .partial_cmp(&self.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| self.vector_id.cmp(&other.vector_id))
+    }
+}
+
+impl PartialOrd for ScoredCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Rebuilds `heap` to hold exactly the current top-`k` entries of `scores`.
+fn refresh_heap(scores: &HashMap<u32, f32>, heap: &mut BinaryHeap<ScoredCandidate>, k: usize) {
+    heap.clear();
+    let mut all: Vec<ScoredCandidate> = scores
+        .iter()
+        .map(|(&vector_id, &score)| ScoredCandidate { vector_id, score })
+        .collect();
+    all.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap()
+            .then_with(|| a.vector_id.cmp(&b.vector_id))
+    });
+    heap.extend(all.into_iter().take(k));
+}
+
+/// A dense map from `vector_id` to `V`, for the common case of bulk ingest where ids are
+/// allocated densely from `0..N` and hashing every id into a [`DashMap`] just adds overhead.
+/// Values live at `slots[vector_id]`; `presence` is a parallel bitmap so membership checks
+/// and iteration don't have to rely on scanning for a sentinel.
+#[derive(Debug, Clone)]
+pub struct IntMap<V> {
+    slots: Vec<V>,
+    presence: Vec<u64>,
+}
+
+impl IntMap<u8> {
+    const EMPTY: u8 = u8::MAX;
+
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            presence: Vec::new(),
+        }
+    }
+
+    fn ensure_capacity(&mut self, id: u32) {
+        let needed = id as usize + 1;
+        if needed > self.slots.len() {
+            self.slots.resize(needed, Self::EMPTY);
+            self.presence.resize(needed.div_ceil(64), 0);
+        }
+    }
+
+    /// Inserts `val` at `id`, growing the backing storage to fit if needed.
+    pub fn insert(&mut self, id: u32, val: u8) {
+        self.ensure_capacity(id);
+        self.slots[id as usize] = val;
+        self.presence[id as usize / 64] |= 1u64 << (id % 64);
+    }
+
+    /// Returns the value at `id`, or `None` if `id` is past the end or was never inserted.
+    pub fn get(&self, id: u32) -> Option<u8> {
+        let word = *self.presence.get(id as usize / 64)?;
+        if word & (1u64 << (id % 64)) == 0 {
+            return None;
+        }
+        Some(self.slots[id as usize])
+    }
+
+    /// Iterates `(id, value)` pairs in ascending id order, walking the presence bitmap and
+    /// peeling off the lowest set bit with `trailing_zeros` each step.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u8)> + '_ {
+        self.presence
+            .iter()
+            .enumerate()
+            .flat_map(move |(word_index, &word)| {
+                let mut remaining = word;
+                std::iter::from_fn(move || {
+                    if remaining == 0 {
+                        return None;
+                    }
+                    let bit = remaining.trailing_zeros();
+                    remaining &= remaining - 1;
+                    let id = word_index as u32 * 64 + bit;
+                    Some((id, self.slots[id as usize]))
+                })
+            })
+    }
+
+    /// Shrinks backing storage to fit the highest inserted id. Call once a version is
+    /// sealed and no further inserts for it are expected.
+    pub fn shrink_to_fit(&mut self) {
+        self.slots.shrink_to_fit();
+        self.presence.shrink_to_fit();
+    }
+}
+
+impl Default for IntMap<u8> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Clone)]
 pub struct InvertedIndexSparseAnnNodeBasicDashMap {
     pub dim_index: u32,
     pub implicit: bool,
-    pub data: DashMap<u32, u8>,
+    pub data: Arc<RwLock<IntMap<u8>>>,
     pub lazy_children: LazyItemArray<InvertedIndexSparseAnnNodeBasicDashMap, 16>,
 }
 
@@ -525,12 +1442,10 @@ pub struct InvertedIndexSparseAnnBasicDashMap {
 
 impl InvertedIndexSparseAnnNodeBasicDashMap {
     pub fn new(dim_index: u32, implicit: bool) -> Self {
-        let data: DashMap<u32, u8> = DashMap::new();
-
         InvertedIndexSparseAnnNodeBasicDashMap {
             dim_index,
             implicit,
-            data,
+            data: Arc::new(RwLock::new(IntMap::new())),
             lazy_children: LazyItemArray::new(),
         }
     }
@@ -578,8 +1493,13 @@ impl InvertedIndexSparseAnnNodeBasicDashMap {
         vector_id: u32,
     ) {
         let quantized_value = Self::quantize(value);
-        let data = node.data.clone();
-        data.insert(vector_id, quantized_value);
+        node.data.write().unwrap().insert(vector_id, quantized_value);
+    }
+
+    /// Drops any slack in the backing storage once this node's version is sealed and no
+    /// further inserts are expected.
+    pub fn shrink_to_fit(&self) {
+        self.data.write().unwrap().shrink_to_fit();
     }
 
     /// Retrieves a value from the index at the specified dimension index.
@@ -601,16 +1521,7 @@ impl InvertedIndexSparseAnnNodeBasicDashMap {
                         .get_value(&path[1..], vector_id, cache)
                 })
                 .flatten(),
-            None => {
-                let res = self.data.get(&vector_id);
-                match res {
-                    Some(val) => {
-                        let p = *val;
-                        return Some(p);
-                    }
-                    None => return None,
-                }
-            }
+            None => self.data.read().unwrap().get(vector_id),
         }
     }
 }
@@ -0,0 +1,555 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+use rayon::prelude::*;
+
+use crate::models::types::SparseVector;
+
+/// Reusable "dense + sparse" accumulator (the classic dense/sparse pair described at
+/// research.swtch.com/sparse) for term-at-a-time sparse query scoring.
+///
+/// `dense` holds touched vector ids in first-touch order, `sparse[id]` points back into
+/// `dense` for ids that have been touched, and `scores` holds one running score per `dense`
+/// slot. Membership is `sparse[id] < dense.len() && dense[sparse[id]] == id`, an O(1) check
+/// that needs no hashing, and `clear` is O(1) since it only truncates `dense`/`scores` rather
+/// than zeroing `sparse` -- stale `sparse` entries are harmless because they're only ever
+/// trusted once `dense.len()` has grown back past them.
+pub struct SparseAccumulator {
+    dense: Vec<u32>,
+    sparse: Vec<u32>,
+    scores: Vec<f32>,
+}
+
+impl SparseAccumulator {
+    /// Creates an accumulator whose backing storage comfortably holds ids up to
+    /// `capacity_hint` without reallocating; it still grows on demand for larger ids.
+    pub fn new(capacity_hint: usize) -> Self {
+        Self {
+            dense: Vec::with_capacity(capacity_hint),
+            sparse: vec![0; capacity_hint],
+            scores: Vec::with_capacity(capacity_hint),
+        }
+    }
+
+    fn slot_of(&self, vector_id: u32) -> Option<usize> {
+        let slot = *self.sparse.get(vector_id as usize)? as usize;
+        if slot < self.dense.len() && self.dense[slot] == vector_id {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+
+    /// Adds `contribution` to `vector_id`'s running score, touching it for the first time
+    /// if this is the first call for that id.
+    pub fn add(&mut self, vector_id: u32, contribution: f32) {
+        if let Some(slot) = self.slot_of(vector_id) {
+            self.scores[slot] += contribution;
+            return;
+        }
+
+        let id = vector_id as usize;
+        if id >= self.sparse.len() {
+            self.sparse.resize(id + 1, 0);
+        }
+        let slot = self.dense.len();
+        self.sparse[id] = slot as u32;
+        self.dense.push(vector_id);
+        self.scores.push(contribution);
+    }
+
+    /// Resets the accumulator for a new query in O(1): `dense`/`scores` are truncated and
+    /// the backing allocations are kept so the next query reuses them.
+    pub fn clear(&mut self) {
+        self.dense.clear();
+        self.scores.clear();
+    }
+
+    /// Iterates `(vector_id, score)` pairs in first-touch (insertion) order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.dense
+            .iter()
+            .zip(self.scores.iter())
+            .map(|(&id, &score)| (id, score))
+    }
+}
+
+impl Default for SparseAccumulator {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// An immutable, CSR-style snapshot of an [`InvertedIndexSparseAnnFlat`]'s postings:
+/// `dim_ptr[d]..dim_ptr[d + 1]` slices `vector_ids`/`values` down to dimension `d`'s postings.
+pub struct CompactSparseIndex {
+    dim_ptr: Vec<usize>,
+    vector_ids: Vec<u32>,
+    values: Vec<f32>,
+}
+
+impl CompactSparseIndex {
+    /// Returns the `(vector_ids, values)` posting list for `dim_index`, or a pair of empty
+    /// slices if the snapshot has no postings that far.
+    pub fn posting_list(&self, dim_index: u32) -> (&[u32], &[f32]) {
+        let dim = dim_index as usize;
+        if dim + 1 >= self.dim_ptr.len() {
+            return (&[], &[]);
+        }
+        let start = self.dim_ptr[dim];
+        let end = self.dim_ptr[dim + 1];
+        (&self.vector_ids[start..end], &self.values[start..end])
+    }
+}
+
+/// Ingest-time policy bounding how large posting lists are allowed to grow.
+#[derive(Debug, Clone, Copy)]
+pub struct SparseIndexConfig {
+    /// Entries whose `value.abs()` is below this are dropped on ingest. `0.0` keeps everything.
+    pub magnitude_threshold: f32,
+    /// Keeps only the `top_k_per_vector` largest-magnitude entries of each ingested vector.
+    /// `None` keeps all of them.
+    pub top_k_per_vector: Option<usize>,
+    /// When set, stored values are scalar-quantized to this many levels (scale derived per
+    /// vector from its largest-magnitude entry). Codes are `i8`, so values above 128 act as
+    /// 128. `None` stores values unquantized.
+    pub quantization_levels: Option<u8>,
+}
+
+impl Default for SparseIndexConfig {
+    fn default() -> Self {
+        Self {
+            magnitude_threshold: 0.0,
+            top_k_per_vector: None,
+            quantization_levels: None,
+        }
+    }
+}
+
+/// A posting value as actually kept in a posting list: either the full-precision weight, or
+/// a quantized fixed-point code when [`SparseIndexConfig::quantization_levels`] is set. The
+/// step a `Quantized` code was computed against lives once per vector in
+/// `InvertedIndexSparseAnnFlat::vector_scales`, not inside the entry itself.
+#[derive(Debug, Clone, Copy)]
+enum StoredValue {
+    Full(f32),
+    Quantized(i8),
+}
+
+impl StoredValue {
+    /// Dequantizes against `step` (the vector's shared quantization step); ignored for `Full`.
+    fn dequantize(self, step: f32) -> f32 {
+        match self {
+            StoredValue::Full(value) => value,
+            StoredValue::Quantized(code) => code as f32 * step,
+        }
+    }
+}
+
+/// Returns the step size that divides `scale` into `levels` quantization steps, with
+/// `levels` capped at 128 so the top code still fits an `i8`.
+fn quantization_step(scale: f32, levels: u8) -> f32 {
+    let max_level = (levels.clamp(2, 128) - 1) as f32;
+    scale / max_level
+}
+
+/// Quantizes `value` to the nearest signed multiple of `step`, clamped to fit an `i8` code.
+fn quantize_to_step(value: f32, step: f32) -> i8 {
+    if step <= 0.0 {
+        return 0;
+    }
+    (value / step).round().clamp(i8::MIN as f32, i8::MAX as f32) as i8
+}
+
+/// A flat, dimension-keyed inverted index over [`SparseVector`]s: postings for dimension
+/// `d` live at `postings[d]` as `(vector_id, value)` pairs, reached with a single map lookup
+/// rather than a tree walk. Each dimension's posting list has its own lock, so appends to
+/// different dimensions never contend with each other.
+pub struct InvertedIndexSparseAnnFlat {
+    postings: RwLock<HashMap<u32, RwLock<Vec<(u32, StoredValue)>>>>,
+    /// Per-vector quantization step, keyed by `vector_id`, for entries stored as
+    /// `StoredValue::Quantized`. Populated on ingest when [`SparseIndexConfig::quantization_levels`]
+    /// is set; unused otherwise.
+    vector_scales: RwLock<HashMap<u32, f32>>,
+    snapshot: RwLock<Option<CompactSparseIndex>>,
+    config: SparseIndexConfig,
+    /// A pool of [`SparseAccumulator`]s that `search_sparse` borrows from and returns to, so
+    /// concurrent queries reuse `dense`/`sparse`/`scores` allocations instead of paying for a
+    /// fresh one per call, without serializing every query behind a single lock the way one
+    /// shared accumulator would.
+    accumulator_pool: Mutex<Vec<SparseAccumulator>>,
+}
+
+impl InvertedIndexSparseAnnFlat {
+    pub fn new() -> Self {
+        Self::with_config(SparseIndexConfig::default())
+    }
+
+    pub fn with_config(config: SparseIndexConfig) -> Self {
+        Self {
+            postings: RwLock::new(HashMap::new()),
+            vector_scales: RwLock::new(HashMap::new()),
+            snapshot: RwLock::new(None),
+            config,
+            accumulator_pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Applies this index's [`SparseIndexConfig`] to one vector's already dim-sorted, nonzero
+    /// entries: drops sub-threshold entries, caps the survivors to the `top_k_per_vector`
+    /// largest in magnitude, and scalar-quantizes what's left if configured to. Returns the
+    /// quantization step alongside the entries so the caller can store it once in
+    /// `vector_scales` instead of per entry.
+    fn apply_ingest_policy(&self, mut entries: Vec<(u32, f32)>) -> (Vec<(u32, StoredValue)>, Option<f32>) {
+        if self.config.magnitude_threshold > 0.0 {
+            let threshold = self.config.magnitude_threshold;
+            entries.retain(|&(_, value)| value.abs() >= threshold);
+        }
+
+        if let Some(k) = self.config.top_k_per_vector {
+            if entries.len() > k {
+                entries.sort_by(|a, b| b.1.abs().partial_cmp(&a.1.abs()).unwrap());
+                entries.truncate(k);
+                entries.sort_by_key(|&(dim_index, _)| dim_index);
+            }
+        }
+
+        match self.config.quantization_levels {
+            Some(levels) => {
+                let scale = entries
+                    .iter()
+                    .map(|&(_, value)| value.abs())
+                    .fold(0.0f32, f32::max)
+                    .max(f32::EPSILON);
+                let step = quantization_step(scale, levels);
+                let quantized = entries
+                    .into_iter()
+                    .map(|(dim_index, value)| (dim_index, StoredValue::Quantized(quantize_to_step(value, step))))
+                    .collect();
+                (quantized, Some(step))
+            }
+            None => {
+                let full = entries
+                    .into_iter()
+                    .map(|(dim_index, value)| (dim_index, StoredValue::Full(value)))
+                    .collect();
+                (full, None)
+            }
+        }
+    }
+
+    /// Builds an immutable [`CompactSparseIndex`] snapshot of the current postings and makes
+    /// it the one `search_sparse` scores against.
+    ///
+    /// Snapshots every bucket's contents under one lock acquisition first, then builds
+    /// `dim_ptr` and the flat arrays from that frozen copy -- re-reading live buckets for a
+    /// sizing pass and a fill pass could see a bucket grow in between and corrupt the layout.
+    pub fn compact(&self) {
+        let snapshot_buckets: Vec<(u32, Vec<(u32, StoredValue)>)> = {
+            let postings = self.postings.read().unwrap();
+            postings
+                .iter()
+                .map(|(&dim_index, bucket)| (dim_index, bucket.read().unwrap().clone()))
+                .collect()
+        };
+
+        let max_dim = snapshot_buckets
+            .iter()
+            .map(|&(dim_index, _)| dim_index)
+            .max()
+            .unwrap_or(0);
+
+        let mut dim_ptr = vec![0usize; max_dim as usize + 2];
+        for (dim_index, entries) in &snapshot_buckets {
+            dim_ptr[*dim_index as usize + 1] = entries.len();
+        }
+        for i in 1..dim_ptr.len() {
+            dim_ptr[i] += dim_ptr[i - 1];
+        }
+
+        let vector_scales = self.vector_scales.read().unwrap();
+
+        let total = dim_ptr[dim_ptr.len() - 1];
+        let mut vector_ids = vec![0u32; total];
+        let mut values = vec![0.0f32; total];
+        let mut cursor = dim_ptr.clone();
+        for (dim_index, entries) in snapshot_buckets {
+            for (vector_id, value) in entries {
+                let step = vector_scales.get(&vector_id).copied().unwrap_or(0.0);
+                let pos = cursor[dim_index as usize];
+                vector_ids[pos] = vector_id;
+                values[pos] = value.dequantize(step);
+                cursor[dim_index as usize] += 1;
+            }
+        }
+
+        *self.snapshot.write().unwrap() = Some(CompactSparseIndex {
+            dim_ptr,
+            vector_ids,
+            values,
+        });
+    }
+
+    /// Appends `(vector_id, value)` to dimension `dim_index`'s posting list, taking the
+    /// dimension's own lock and only falling back to the index-wide lock the first time a
+    /// dimension is seen.
+    fn append_entry(&self, dim_index: u32, vector_id: u32, value: StoredValue) {
+        if let Some(bucket) = self.postings.read().unwrap().get(&dim_index) {
+            bucket.write().unwrap().push((vector_id, value));
+            return;
+        }
+
+        self.postings
+            .write()
+            .unwrap()
+            .entry(dim_index)
+            .or_insert_with(|| RwLock::new(Vec::new()))
+            .write()
+            .unwrap()
+            .push((vector_id, value));
+    }
+
+    /// Adds a sparse vector's nonzero entries to their respective dimensions' posting lists,
+    /// after applying this index's [`SparseIndexConfig`] ingest policy.
+    pub fn add_sparse_vector(&self, mut vector: SparseVector) {
+        // `SparseVector::dot`'s two-pointer merge requires `entries` sorted by `dim_index`;
+        // normalize here so every vector that passes through the index satisfies it.
+        vector.entries.sort_by_key(|&(dim_index, _)| dim_index);
+        vector.entries.retain(|&(_, value)| value != 0.0);
+        let (entries, step) = self.apply_ingest_policy(vector.entries);
+
+        if let Some(step) = step {
+            self.vector_scales.write().unwrap().insert(vector.vector_id, step);
+        }
+
+        for (dim_index, value) in entries {
+            self.append_entry(dim_index, vector.vector_id, value);
+        }
+    }
+
+    /// Bulk-inserts `batch`, grouping entries by `dim_index` first so each posting list's lock
+    /// is taken once for the whole batch rather than once per vector.
+    pub fn add_sparse_vectors(&self, batch: &[SparseVector]) {
+        let mut by_dim: HashMap<u32, Vec<(u32, StoredValue)>> = HashMap::new();
+        let mut scales: Vec<(u32, f32)> = Vec::new();
+        for vector in batch {
+            let mut entries = vector.entries.clone();
+            entries.sort_by_key(|&(dim_index, _)| dim_index);
+            entries.retain(|&(_, value)| value != 0.0);
+            let (entries, step) = self.apply_ingest_policy(entries);
+            if let Some(step) = step {
+                scales.push((vector.vector_id, step));
+            }
+            for (dim_index, value) in entries {
+                by_dim.entry(dim_index).or_default().push((vector.vector_id, value));
+            }
+        }
+
+        if !scales.is_empty() {
+            self.vector_scales.write().unwrap().extend(scales);
+        }
+
+        // Ensure every touched dimension has a posting list up front, under a single
+        // index-wide write lock, so the per-dimension appends below only ever need the
+        // index-wide read lock plus that dimension's own lock.
+        {
+            let mut postings = self.postings.write().unwrap();
+            for &dim_index in by_dim.keys() {
+                postings
+                    .entry(dim_index)
+                    .or_insert_with(|| RwLock::new(Vec::new()));
+            }
+        }
+
+        let postings = self.postings.read().unwrap();
+        for (dim_index, mut entries) in by_dim {
+            let mut bucket = postings[&dim_index].write().unwrap();
+            bucket.append(&mut entries);
+            bucket.sort_by_key(|&(vector_id, _)| vector_id);
+        }
+    }
+
+    /// Finds the `top_k` stored vectors with the highest dot-product score against `query`,
+    /// scoring term-at-a-time with a [`SparseAccumulator`] borrowed from `accumulator_pool` so
+    /// repeated queries reuse its allocations instead of building a fresh one per call, while
+    /// concurrent queries still run in parallel rather than queuing behind one shared instance.
+    /// Scores against the compact CSR snapshot from [`compact`](Self::compact) when one exists,
+    /// falling back to the live postings map otherwise.
+    pub fn search_sparse(&self, query: &SparseVector, top_k: usize) -> Vec<(u32, f32)> {
+        let mut accumulator = self.accumulator_pool.lock().unwrap().pop().unwrap_or_default();
+        accumulator.clear();
+
+        if let Some(snapshot) = &*self.snapshot.read().unwrap() {
+            for &(dim_index, weight) in &query.entries {
+                if weight == 0.0 {
+                    continue;
+                }
+                let (vector_ids, values) = snapshot.posting_list(dim_index);
+                for (&vector_id, &value) in vector_ids.iter().zip(values) {
+                    accumulator.add(vector_id, weight * value);
+                }
+            }
+        } else {
+            let postings = self.postings.read().unwrap();
+            let vector_scales = self.vector_scales.read().unwrap();
+            for &(dim_index, weight) in &query.entries {
+                if weight == 0.0 {
+                    continue;
+                }
+                if let Some(bucket) = postings.get(&dim_index) {
+                    for &(vector_id, value) in bucket.read().unwrap().iter() {
+                        let step = vector_scales.get(&vector_id).copied().unwrap_or(0.0);
+                        accumulator.add(vector_id, weight * value.dequantize(step));
+                    }
+                }
+            }
+        }
+
+        let mut scored: Vec<(u32, f32)> = accumulator.iter().collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        scored.truncate(top_k);
+
+        self.accumulator_pool.lock().unwrap().push(accumulator);
+
+        scored
+    }
+}
+
+impl Default for InvertedIndexSparseAnnFlat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseVector {
+    /// Exact dot product against `other`, as a two-pointer merge over both `entries` lists.
+    /// Requires both to be sorted by `dim_index`.
+    pub fn dot(&self, other: &SparseVector) -> f32 {
+        debug_assert!(is_sorted_by_dim(&self.entries), "entries must be sorted by dim_index");
+        debug_assert!(is_sorted_by_dim(&other.entries), "entries must be sorted by dim_index");
+
+        let mut i = 0;
+        let mut j = 0;
+        let mut sum = 0.0f32;
+
+        while i < self.entries.len() && j < other.entries.len() {
+            let (dim_a, val_a) = self.entries[i];
+            let (dim_b, val_b) = other.entries[j];
+            match dim_a.cmp(&dim_b) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    sum += val_a * val_b;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        sum
+    }
+
+    /// Scores `self` against every candidate in parallel, for rescoring after an approximate
+    /// inverted-index prefilter.
+    pub fn dot_batch(&self, candidates: &[SparseVector]) -> Vec<f32> {
+        candidates.par_iter().map(|candidate| self.dot(candidate)).collect()
+    }
+}
+
+fn is_sorted_by_dim(entries: &[(u32, f32)]) -> bool {
+    entries.windows(2).all(|pair| pair[0].0 <= pair[1].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(vector_id: u32, entries: &[(u32, f32)]) -> SparseVector {
+        SparseVector {
+            vector_id,
+            entries: entries.to_vec(),
+        }
+    }
+
+    #[test]
+    fn compact_snapshot_matches_live_search() {
+        let index = InvertedIndexSparseAnnFlat::new();
+        index.add_sparse_vector(vector(1, &[(0, 1.0), (1, 2.0)]));
+        index.add_sparse_vector(vector(2, &[(0, 0.5), (2, 4.0)]));
+        index.add_sparse_vector(vector(3, &[(1, 1.0), (2, 1.0)]));
+
+        let query = vector(99, &[(0, 1.0), (1, 1.0), (2, 1.0)]);
+        let live = index.search_sparse(&query, 10);
+
+        index.compact();
+        let compacted = index.search_sparse(&query, 10);
+
+        assert_eq!(live, compacted);
+    }
+
+    #[test]
+    fn search_sparse_reuses_accumulator_without_leaking_state_across_queries() {
+        let index = InvertedIndexSparseAnnFlat::new();
+        index.add_sparse_vector(vector(1, &[(0, 1.0)]));
+        index.add_sparse_vector(vector(2, &[(1, 5.0)]));
+
+        let first = index.search_sparse(&vector(99, &[(1, 1.0)]), 10);
+        assert_eq!(first, vec![(2, 5.0)]);
+
+        // A second, disjoint query must not see vector 2's score bleed in from the shared
+        // accumulator's state left over by the first call.
+        let second = index.search_sparse(&vector(99, &[(0, 1.0)]), 10);
+        assert_eq!(second, vec![(1, 1.0)]);
+    }
+
+    #[test]
+    fn compact_dim_ptr_slices_line_up_per_dimension() {
+        let index = InvertedIndexSparseAnnFlat::new();
+        index.add_sparse_vector(vector(1, &[(0, 1.0)]));
+        index.add_sparse_vector(vector(2, &[(0, 1.0), (3, 2.0)]));
+        index.add_sparse_vector(vector(3, &[(3, 3.0)]));
+        index.compact();
+
+        let query = vector(99, &[(0, 1.0)]);
+        let mut scored = index.search_sparse(&query, 10);
+        scored.sort_by_key(|&(id, _)| id);
+        assert_eq!(scored, vec![(1, 1.0), (2, 1.0)]);
+
+        let query = vector(99, &[(3, 1.0)]);
+        let mut scored = index.search_sparse(&query, 10);
+        scored.sort_by_key(|&(id, _)| id);
+        assert_eq!(scored, vec![(2, 2.0), (3, 3.0)]);
+    }
+
+    #[test]
+    fn quantization_stores_a_lossy_but_reconstructible_code_rather_than_dropping_precision_for_nothing() {
+        let config = SparseIndexConfig {
+            quantization_levels: Some(64),
+            ..SparseIndexConfig::default()
+        };
+        let index = InvertedIndexSparseAnnFlat::with_config(config);
+        index.add_sparse_vector(vector(1, &[(0, 1.0), (1, -0.5)]));
+
+        let scored = index.search_sparse(&vector(99, &[(0, 1.0)]), 10);
+        assert_eq!(scored.len(), 1);
+        assert_eq!(scored[0].0, 1);
+        // The largest-magnitude entry quantizes to the top step, so it should come back close
+        // to (but, since quantization is lossy, not necessarily exactly) the original value.
+        assert!((scored[0].1 - 1.0).abs() < 0.05);
+
+        let scored = index.search_sparse(&vector(99, &[(1, 1.0)]), 10);
+        assert_eq!(scored.len(), 1);
+        assert!((scored[0].1 - (-0.5)).abs() < 0.05);
+    }
+
+    #[test]
+    fn quantize_to_step_reconstructs_within_half_a_step() {
+        let step = quantization_step(1.0, 64);
+        for code in [-100, -1, 0, 1, 42, 100] {
+            let value = code as f32 * step;
+            let reconstructed = quantize_to_step(value, step) as f32 * step;
+            assert!((reconstructed - value).abs() <= step / 2.0 + f32::EPSILON);
+        }
+    }
+}